@@ -78,7 +78,7 @@ pub enum Error {
     CouldNotRead { path: PathBuf, source: io::Error },
     #[error("Invalid toml file.")]
     InvalidToml { source: toml::de::Error },
-    #[error("Could not find `{crate_name}` in `dependencies` or `dev-dependencies` in `{path}`!")]
+    #[error("Could not find `{crate_name}` in `dependencies`, `dev-dependencies` or `build-dependencies` in `{path}`!")]
     CrateNotFound { crate_name: String, path: PathBuf },
 }
 
@@ -91,6 +91,101 @@ pub enum FoundCrate {
     Name(String),
 }
 
+/// A parsed `Cargo.toml` that can be queried for crate names repeatedly.
+///
+/// In contrast to [`crate_name`], which resolves a single crate by exact name, a `Manifest`
+/// parses the `Cargo.toml` once and lets the caller run arbitrary lookups against the resulting
+/// table. This is useful for a derive macro that accepts a crate exported under several possible
+/// names (e.g. `foo` or `foo-core`) and wants to resolve it without rebuilding the lookup table
+/// for every candidate.
+pub struct Manifest {
+    manifest_path: PathBuf,
+    crate_names: BTreeMap<String, (FoundCrate, Option<String>)>,
+}
+
+impl Manifest {
+    /// Create a `Manifest` from `CARGO_MANIFEST_DIR/Cargo.toml`.
+    pub fn new() -> Result<Self, Error> {
+        let manifest_dir =
+            env::var("CARGO_MANIFEST_DIR").map_err(|_| Error::CargoManifestDirNotSet)?;
+        let manifest_path = Path::new(&manifest_dir).join("Cargo.toml");
+
+        if !manifest_path.exists() {
+            return Err(Error::NotFound(manifest_dir.into()));
+        }
+
+        Self::from_path(manifest_path)
+    }
+
+    fn from_path(manifest_path: PathBuf) -> Result<Self, Error> {
+        let manifest = open_cargo_toml(&manifest_path)?;
+
+        // Only locate and parse the workspace root when a dependency actually inherits from it.
+        // Otherwise a crate without any `workspace = true` dependency would needlessly read every
+        // ancestor `Cargo.toml` and could even fail on an unrelated invalid one.
+        let workspace_manifest = if has_inherited_dependency(&manifest) {
+            open_workspace_manifest(&manifest_path, &manifest)?
+        } else {
+            None
+        };
+        let workspace_deps = workspace_manifest
+            .as_ref()
+            .and_then(workspace_dep_table);
+        let crate_names = extract_crate_names(&manifest, workspace_deps)?;
+
+        Ok(Self {
+            manifest_path,
+            crate_names,
+        })
+    }
+
+    /// Find the first crate whose original name satisfies `predicate`.
+    ///
+    /// The original name is the name the crate is published under, i.e. the one a caller would
+    /// pass to [`crate_name`]. Returns `None` if no dependency matches.
+    pub fn find(&self, mut predicate: impl FnMut(&str) -> bool) -> Option<FoundCrate> {
+        self.crate_names
+            .iter()
+            .find(|(orig_name, _)| predicate(orig_name))
+            .map(|(_, (found, _))| found.clone())
+    }
+
+    /// Like [`Manifest::find`], but the predicate also receives the version requirement the crate
+    /// is declared with in the `Cargo.toml`, and the matched version requirement is returned
+    /// alongside the [`FoundCrate`].
+    ///
+    /// The version is the raw requirement string (e.g. `"1"` or `">=0.3, <0.5"`), or `None` when
+    /// the dependency does not specify one (the predicate sees the empty string in that case).
+    /// This lets a macro that supports several major versions of a target crate both select and
+    /// inspect the matching code path at expansion time.
+    ///
+    /// The version is returned next to the `FoundCrate` rather than embedded in
+    /// [`FoundCrate::Name`] so that the public `FoundCrate` shape stays unchanged and existing
+    /// `match FoundCrate::Name(name)` call sites keep compiling.
+    pub fn find2(
+        &self,
+        mut predicate: impl FnMut(&str, &str) -> bool,
+    ) -> Option<(FoundCrate, Option<String>)> {
+        self.crate_names
+            .iter()
+            .find(|(orig_name, (_, version))| {
+                predicate(orig_name, version.as_deref().unwrap_or(""))
+            })
+            .map(|(_, (found, version))| (found.clone(), version.clone()))
+    }
+
+    /// Resolve several crates by their original name in a single pass.
+    ///
+    /// The returned map only contains an entry for those of the given `orig_names` that are
+    /// present in the `Cargo.toml`.
+    pub fn crate_names<'a>(&self, orig_names: &[&'a str]) -> BTreeMap<&'a str, FoundCrate> {
+        orig_names
+            .iter()
+            .filter_map(|&name| Some((name, self.crate_names.get(name)?.0.clone())))
+            .collect()
+    }
+}
+
 /// Find the crate name for the given `orig_name` in the current `Cargo.toml`.
 ///
 /// `orig_name` should be the original name of the searched crate.
@@ -111,26 +206,16 @@ pub fn crate_name(orig_name: &str) -> Result<FoundCrate, Error> {
 
     struct Cache {
         manifest_dir: String,
-        manifest_path: PathBuf,
-        crate_names: BTreeMap<String, FoundCrate>,
+        manifest: Manifest,
     }
 
     static CACHE: OnceCell<Cache> = OnceCell::new();
     let cache = CACHE.get_or_try_init(|| {
-        let manifest_dir = manifest_dir.clone();
-        let manifest_path = Path::new(&manifest_dir).join("Cargo.toml");
+        let manifest = Manifest::new()?;
 
-        if !manifest_path.exists() {
-            return Err(Error::NotFound(manifest_dir.into()));
-        }
-
-        let manifest = open_cargo_toml(&manifest_path)?;
-        let crate_names = extract_crate_names(&manifest)?;
-
-        Ok(Cache {
-            manifest_dir,
-            manifest_path,
-            crate_names,
+        Ok::<_, Error>(Cache {
+            manifest_dir: manifest_dir.clone(),
+            manifest,
         })
     })?;
 
@@ -140,12 +225,14 @@ pub fn crate_name(orig_name: &str) -> Result<FoundCrate, Error> {
     );
 
     Ok(cache
+        .manifest
         .crate_names
         .get(orig_name)
         .ok_or_else(|| Error::CrateNotFound {
             crate_name: orig_name.to_owned(),
-            path: cache.manifest_path.clone(),
+            path: cache.manifest.manifest_path.clone(),
         })?
+        .0
         .clone())
 }
 
@@ -170,39 +257,177 @@ fn open_cargo_toml(path: &Path) -> Result<Table, Error> {
     toml::from_str(&content).map_err(|e| Error::InvalidToml { source: e })
 }
 
-/// Extract all crate names from the given `Cargo.toml` by checking the `dependencies` and
-/// `dev-dependencies`.
-fn extract_crate_names(cargo_toml: &Table) -> Result<BTreeMap<String, FoundCrate>, Error> {
+/// Extract all crate names from the given `Cargo.toml` by checking the `dependencies`,
+/// `dev-dependencies` and `build-dependencies`.
+///
+/// `workspace_deps` is the `[workspace.dependencies]` table of the workspace root, if any. It is
+/// used to resolve dependencies that inherit their spec via `workspace = true`.
+fn extract_crate_names(
+    cargo_toml: &Table,
+    workspace_deps: Option<&Table>,
+) -> Result<BTreeMap<String, (FoundCrate, Option<String>)>, Error> {
     let package_name = extract_package_name(cargo_toml);
-    let root_pkg = package_name.map(|name| {
-        let cr = match env::var_os("CARGO_TARGET_TMPDIR") {
-            // We're running for a library/binary crate
-            None => FoundCrate::Itself,
-            // We're running for an integration test
-            Some(_) => FoundCrate::Name(sanitize_crate_name(name)),
-        };
-
-        (name.to_owned(), cr)
-    });
+    let root_pkg = package_name.map(|name| (name.to_owned(), (root_found_crate(name), None)));
 
     let dep_tables = dep_tables(cargo_toml).chain(target_dep_tables(cargo_toml));
-    let dep_pkgs = dep_tables.flatten().map(|(dep_name, dep_value)| {
-        let pkg_name = dep_value
+    let dep_pkgs = dep_tables.flatten().map(move |(dep_name, dep_value)| {
+        // A dependency can inherit its spec (including a `package = "..."` rename) from the
+        // workspace root via `workspace = true`. In that case the real package name lives in the
+        // `[workspace.dependencies]` table, while the member-side key stays the import identifier.
+        let workspace_entry = dep_value
             .as_table()
-            .and_then(|t| t.get("package")?.as_str())
-            .unwrap_or(dep_name);
+            .filter(|t| t.get("workspace").and_then(toml::Value::as_bool) == Some(true))
+            .and_then(|_| workspace_deps?.get(dep_name));
+
+        let spec = workspace_entry.unwrap_or(dep_value);
+        let pkg_name = package_name_from_dep(spec).unwrap_or(dep_name);
+        let version = version_from_dep(spec).map(str::to_owned);
         let cr = FoundCrate::Name(sanitize_crate_name(dep_name));
 
-        (pkg_name.to_owned(), cr)
+        (pkg_name.to_owned(), (cr, version))
     });
 
     Ok(root_pkg.into_iter().chain(dep_pkgs).collect())
 }
 
+/// Determine how the root package should refer to itself for the target currently being compiled.
+///
+/// A library or binary build of the package itself uses `crate::`, i.e. [`FoundCrate::Itself`].
+/// Integration tests, examples and benchmarks are compiled as their own crate and instead refer to
+/// the package by its real name, so they get [`FoundCrate::Name`] with the sanitized package name.
+fn root_found_crate(name: &str) -> FoundCrate {
+    classify_root(
+        name,
+        compilation_src_dir().as_deref(),
+        env::var_os("CARGO_TARGET_TMPDIR").is_some(),
+    )
+}
+
+/// The name of the directory containing the crate root source file of the target currently being
+/// compiled (e.g. `examples` for `examples/foo.rs`). For a multi-file target rooted at
+/// `main.rs` inside `tests/`, `examples/` or `benches/`, the outer directory is returned instead
+/// (e.g. `examples` for `examples/foo/main.rs`).
+///
+/// `rustc` is invoked with the crate root source file as a positional argument, and proc-macros
+/// run inside that `rustc` process, so the path is visible through [`env::args`].
+fn compilation_src_dir() -> Option<String> {
+    let path = PathBuf::from(env::args().find(|arg| arg.ends_with(".rs"))?);
+    let mut dirs = path.ancestors().skip(1).map(|dir| dir.file_name()?.to_str());
+    let parent = dirs.next()??;
+    // A multi-file target such as `examples/foo/main.rs` lives one directory deeper.
+    match dirs.next().flatten() {
+        Some(outer)
+            if path.ends_with("main.rs") && matches!(outer, "tests" | "examples" | "benches") =>
+        {
+            Some(outer.to_owned())
+        }
+        _ => Some(parent.to_owned()),
+    }
+}
+
+/// Decide the root-package [`FoundCrate`] from the target kind currently being compiled.
+///
+/// `src_dir` is the directory of the crate root source file (see [`compilation_src_dir`]) and
+/// `has_target_tmpdir` whether `CARGO_TARGET_TMPDIR` is set. A library or binary build of the
+/// package refers to itself via `crate::`, i.e. [`FoundCrate::Itself`] — this holds regardless of
+/// a custom `[lib] name` or `[[bin]] name`, whose source still lives under `src/`. Integration
+/// tests, examples and benchmarks are compiled as their own crate (source under `tests/`,
+/// `examples/`, `benches/`, or with a dedicated temp dir) and refer to the package by its real
+/// name, so they get [`FoundCrate::Name`].
+fn classify_root(name: &str, src_dir: Option<&str>, has_target_tmpdir: bool) -> FoundCrate {
+    let is_external_target =
+        has_target_tmpdir || matches!(src_dir, Some("tests") | Some("examples") | Some("benches"));
+
+    if is_external_target {
+        FoundCrate::Name(sanitize_crate_name(name))
+    } else {
+        FoundCrate::Itself
+    }
+}
+
 fn extract_package_name(cargo_toml: &Table) -> Option<&str> {
     cargo_toml.get("package")?.as_table()?.get("name")?.as_str()
 }
 
+/// Read the `package` rename from a dependency entry, supporting both the table form
+/// (`{ package = "..." }`) and — when the spec is inherited — a workspace entry.
+fn package_name_from_dep(dep_value: &toml::Value) -> Option<&str> {
+    dep_value.as_table()?.get("package")?.as_str()
+}
+
+/// Read the version requirement from a dependency entry, supporting both the bare string form
+/// (`foo = "1"`) and the table form (`foo = { version = "1" }`).
+fn version_from_dep(dep_value: &toml::Value) -> Option<&str> {
+    match dep_value {
+        toml::Value::String(version) => Some(version),
+        toml::Value::Table(table) => table.get("version")?.as_str(),
+        _ => None,
+    }
+}
+
+/// Find and parse the workspace root `Cargo.toml` for the given member manifest.
+///
+/// If the manifest declares its own `[workspace]` table it is the workspace root. Otherwise an
+/// explicit `package.workspace` path override is honored, and failing that the parent directories
+/// of the member manifest are walked upward until a `Cargo.toml` with a `[workspace]` table is
+/// found. Returns `Ok(None)` if no workspace could be located.
+fn open_workspace_manifest(
+    manifest_path: &Path,
+    manifest: &Table,
+) -> Result<Option<Table>, Error> {
+    // A single-crate package can be its own workspace root, declaring `[workspace]` and
+    // `[workspace.dependencies]` in the very `Cargo.toml` that inherits them via `workspace = true`.
+    if manifest.contains_key("workspace") {
+        return Ok(Some(manifest.clone()));
+    }
+
+    let manifest_dir = manifest_path.parent().unwrap_or_else(|| Path::new(""));
+
+    // An explicit path override points directly at the workspace directory.
+    let explicit = manifest
+        .get("package")
+        .and_then(toml::Value::as_table)
+        .and_then(|t| t.get("workspace")?.as_str());
+
+    if let Some(path) = explicit {
+        let ws_path = manifest_dir.join(path).join("Cargo.toml");
+        return open_cargo_toml(&ws_path).map(Some);
+    }
+
+    for dir in manifest_dir.ancestors() {
+        let ws_path = dir.join("Cargo.toml");
+        if ws_path == manifest_path || !ws_path.exists() {
+            continue;
+        }
+
+        let ws_manifest = open_cargo_toml(&ws_path)?;
+        if ws_manifest.contains_key("workspace") {
+            return Ok(Some(ws_manifest));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Whether any dependency of the manifest inherits its spec from the workspace via
+/// `workspace = true`.
+fn has_inherited_dependency(cargo_toml: &Table) -> bool {
+    dep_tables(cargo_toml)
+        .chain(target_dep_tables(cargo_toml))
+        .flat_map(|t| t.values())
+        .filter_map(toml::Value::as_table)
+        .any(|t| t.get("workspace").and_then(toml::Value::as_bool) == Some(true))
+}
+
+/// Get the `[workspace.dependencies]` table from a parsed workspace manifest.
+fn workspace_dep_table(workspace_manifest: &Table) -> Option<&Table> {
+    workspace_manifest
+        .get("workspace")?
+        .as_table()?
+        .get("dependencies")?
+        .as_table()
+}
+
 fn target_dep_tables(cargo_toml: &Table) -> impl Iterator<Item = &Table> {
     cargo_toml
         .get("target")
@@ -220,6 +445,7 @@ fn dep_tables(table: &Table) -> impl Iterator<Item = &Table> {
         .get("dependencies")
         .into_iter()
         .chain(table.get("dev-dependencies"))
+        .chain(table.get("build-dependencies"))
         .filter_map(toml::Value::as_table)
 }
 
@@ -237,7 +463,9 @@ mod tests {
             fn $name() {
                 let cargo_toml = toml::from_str($cargo_toml).expect("Parses `Cargo.toml`");
 
-                match extract_crate_names(&cargo_toml).map(|mut map| map.remove("my_crate")) {
+                match extract_crate_names(&cargo_toml, None)
+                    .map(|mut map| map.remove("my_crate").map(|(cr, _)| cr))
+                {
                     $( $result )* => (),
                     o => panic!("Invalid result: {:?}", o),
                 }
@@ -263,6 +491,15 @@ mod tests {
         Ok(Some(FoundCrate::Name(name))) if name == "my_crate"
     }
 
+    create_test! {
+        build_deps_with_crate,
+        r#"
+            [build-dependencies]
+            my_crate = "0.1"
+        "#,
+        Ok(Some(FoundCrate::Name(name))) if name == "my_crate"
+    }
+
     create_test! {
         deps_with_crate_renamed,
         r#"
@@ -309,20 +546,169 @@ mod tests {
     }
 
     create_test! {
-        target_dependency2,
+        target_build_dependency,
         r#"
-            [target.x86_64-pc-windows-gnu.dependencies]
+            [target.'cfg(target_os="android")'.build-dependencies]
             my_crate = "0.1"
         "#,
         Ok(Some(FoundCrate::Name(name))) if name == "my_crate"
     }
 
     create_test! {
-        own_crate,
+        target_dependency2,
         r#"
-            [package]
-            name = "my_crate"
+            [target.x86_64-pc-windows-gnu.dependencies]
+            my_crate = "0.1"
         "#,
-        Ok(Some(FoundCrate::Itself))
+        Ok(Some(FoundCrate::Name(name))) if name == "my_crate"
+    }
+
+    #[test]
+    fn root_crate_classification() {
+        // Library build (`src/lib.rs`) refers to itself via `crate::`, even with a custom
+        // `[lib] name` whose crate name differs from the package name.
+        assert_eq!(classify_root("my_crate", Some("src"), false), FoundCrate::Itself);
+        // Binary builds (`src/main.rs`, `src/bin/cli.rs`) likewise refer to themselves.
+        assert_eq!(classify_root("my_crate", Some("bin"), false), FoundCrate::Itself);
+        // Unknown source dir falls back to a lib/bin build.
+        assert_eq!(classify_root("my_crate", None, false), FoundCrate::Itself);
+        // Integration tests, examples and benchmarks refer to the package by its real name.
+        assert_eq!(
+            classify_root("my-crate", Some("tests"), false),
+            FoundCrate::Name("my_crate".into())
+        );
+        assert_eq!(
+            classify_root("my_crate", Some("examples"), false),
+            FoundCrate::Name("my_crate".into())
+        );
+        assert_eq!(
+            classify_root("my_crate", Some("benches"), false),
+            FoundCrate::Name("my_crate".into())
+        );
+        // A dedicated temp dir (e.g. the integration-test harness) is also sufficient.
+        assert_eq!(
+            classify_root("my_crate", Some("src"), true),
+            FoundCrate::Name("my_crate".into())
+        );
+    }
+
+    #[test]
+    fn workspace_inherited_dependency() {
+        let cargo_toml = toml::from_str(
+            r#"
+                [dependencies]
+                my_crate = { workspace = true }
+            "#,
+        )
+        .expect("Parses `Cargo.toml`");
+        let workspace = toml::from_str::<Table>(
+            r#"
+                [workspace.dependencies]
+                my_crate = "0.1"
+            "#,
+        )
+        .expect("Parses workspace `Cargo.toml`");
+        let workspace_deps = workspace_dep_table(&workspace);
+
+        let mut map = extract_crate_names(&cargo_toml, workspace_deps).expect("Extracts names");
+        assert_eq!(
+            map.remove("my_crate").map(|(cr, _)| cr),
+            Some(FoundCrate::Name("my_crate".into()))
+        );
+    }
+
+    #[test]
+    fn workspace_inherited_dependency_renamed() {
+        let cargo_toml = toml::from_str(
+            r#"
+                [dependencies]
+                cool.workspace = true
+            "#,
+        )
+        .expect("Parses `Cargo.toml`");
+        let workspace = toml::from_str::<Table>(
+            r#"
+                [workspace.dependencies]
+                cool = { package = "my_crate", version = "0.1" }
+            "#,
+        )
+        .expect("Parses workspace `Cargo.toml`");
+        let workspace_deps = workspace_dep_table(&workspace);
+
+        let mut map = extract_crate_names(&cargo_toml, workspace_deps).expect("Extracts names");
+        assert_eq!(
+            map.remove("my_crate").map(|(cr, _)| cr),
+            Some(FoundCrate::Name("cool".into()))
+        );
+    }
+
+    #[test]
+    fn workspace_inheritance_from_fixture() {
+        let manifest_dir = env::var("CARGO_MANIFEST_DIR").expect("Set when running tests");
+        let manifest_path = Path::new(&manifest_dir)
+            .join("tests")
+            .join("workspace_deps")
+            .join("my-cool-dep")
+            .join("Cargo.toml");
+
+        let manifest = open_cargo_toml(&manifest_path).expect("Fixture manifest exists");
+        let workspace = open_workspace_manifest(&manifest_path, &manifest)
+            .expect("No io error")
+            .expect("Workspace root located by walking parents");
+        let workspace_deps = workspace_dep_table(&workspace);
+
+        let mut map = extract_crate_names(&manifest, workspace_deps).expect("Extracts names");
+        assert_eq!(
+            map.remove("my-cool-dep-real-name").map(|(cr, _)| cr),
+            Some(FoundCrate::Name("my_cool_dep".into()))
+        );
+    }
+
+    #[test]
+    fn workspace_root_is_the_member_itself() {
+        // A single crate that is its own workspace root must resolve inherited deps from the same
+        // manifest, without walking to a parent directory.
+        let manifest = toml::from_str::<Table>(
+            r#"
+                [package]
+                name = "host"
+
+                [workspace]
+
+                [workspace.dependencies]
+                cool = { package = "my_crate" }
+
+                [dependencies]
+                cool.workspace = true
+            "#,
+        )
+        .expect("Parses `Cargo.toml`");
+
+        let workspace = open_workspace_manifest(Path::new("Cargo.toml"), &manifest)
+            .expect("No io error")
+            .expect("Manifest is its own workspace root");
+        let workspace_deps = workspace_dep_table(&workspace);
+
+        let mut map = extract_crate_names(&manifest, workspace_deps).expect("Extracts names");
+        assert_eq!(
+            map.remove("my_crate").map(|(cr, _)| cr),
+            Some(FoundCrate::Name("cool".into()))
+        );
+    }
+
+    #[test]
+    fn dependency_version_is_recorded() {
+        let cargo_toml = toml::from_str::<Table>(
+            r#"
+                [dependencies]
+                my_crate = "1"
+                renamed = { package = "other_crate", version = "2" }
+            "#,
+        )
+        .expect("Parses `Cargo.toml`");
+
+        let map = extract_crate_names(&cargo_toml, None).expect("Extracts names");
+        assert_eq!(map.get("my_crate").and_then(|(_, v)| v.as_deref()), Some("1"));
+        assert_eq!(map.get("other_crate").and_then(|(_, v)| v.as_deref()), Some("2"));
     }
 }